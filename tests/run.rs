@@ -0,0 +1,141 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use clap::Parser;
+use nrdata_dl::{run, Args, DownloadConfig};
+
+/// Answer each request with the data of the NR in the path, except for `404`,
+/// which is not found.
+fn mock_api(requests: usize) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for _ in 0..requests {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            while reader.read_line(&mut request).unwrap() > 2 {}
+            let nr = request
+                .split_whitespace()
+                .nth(1)
+                .unwrap()
+                .trim_start_matches('/');
+            let (status, body) = match nr {
+                "404" => ("404 Not Found", String::new()),
+                nr => ("200 OK", format!(r#"{{"nr":"{}"}}"#, nr)),
+            };
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    url
+}
+
+#[test]
+fn run_downloads_from_api() {
+    let url = mock_api(3);
+    let output_folder = "test_run/";
+    let args = Args::parse_from(vec![
+        "nrdata-dl",
+        "--api-url",
+        &url,
+        "--output-folder",
+        output_folder,
+        "--limit-per-minute",
+        "60000",
+        "--nr",
+        "12.3",
+        "--nr",
+        "45",
+        "--nr",
+        "404",
+        "--no-progress",
+    ]);
+    let config = DownloadConfig::resolve(args, |_| None).unwrap();
+    let report = run(&config);
+    let content = std::fs::read_to_string("test_run/123.json");
+    let summary = std::fs::read_to_string("test_run/summary.csv");
+    std::fs::remove_dir_all(output_folder).unwrap();
+
+    let report = report.unwrap();
+    assert_eq!(report.total, 3);
+    assert_eq!(report.downloaded, 2);
+    assert_eq!(report.not_found, 1);
+    assert!(!report.interrupted);
+    assert_eq!(content.unwrap(), r#"{"nr":"123"}"#);
+    assert_eq!(summary.unwrap().lines().count(), 4);
+}
+
+#[test]
+fn only_data_and_report_go_to_stdout() {
+    let url = mock_api(2);
+    let output_folder = "test_streams/";
+    let output = Command::new(env!("CARGO_BIN_EXE_nrdata-dl"))
+        .args([
+            "--api-url",
+            &url,
+            "--output-folder",
+            output_folder,
+            "--limit-per-minute",
+            "60000",
+            "--nr",
+            "12",
+            "--nr",
+            "404",
+            "--output-stdout",
+            "--report-json",
+            "-",
+        ])
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(output_folder).unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let (data, report) = stdout.split_once('\n').unwrap();
+    assert_eq!(data, r#"{"data":{"nr":"12"},"nr":"12"}"#);
+    let report: serde_json::Value = serde_json::from_str(report).unwrap();
+    assert_eq!(report["downloaded"], 1);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("was not found."));
+    assert!(stderr.contains("All done."));
+}
+
+#[test]
+fn nrs_are_read_from_stdin() {
+    let url = mock_api(1);
+    let output_folder = "test_stdin/";
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nrdata-dl"))
+        .args([
+            "--api-url",
+            &url,
+            "--output-folder",
+            output_folder,
+            "--limit-per-minute",
+            "60000",
+            "--input-file",
+            "-",
+        ])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"# From a pipe\n12.3\n")
+        .unwrap();
+    let status = child.wait().unwrap();
+    let content = std::fs::read_to_string("test_stdin/123.json");
+    std::fs::remove_dir_all(output_folder).unwrap();
+
+    assert!(status.success());
+    assert_eq!(content.unwrap(), r#"{"nr":"123"}"#);
+}